@@ -0,0 +1,131 @@
+use crate::{AvgData, MapInfo};
+use megabase_index_incrementer::FactorioVersion;
+use memmap2::Mmap;
+use rkyv::Deserialize as _;
+use std::collections::{BTreeMap, HashMap};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+/// `FactorioVersion` lives in `megabase_index_incrementer`, so the orphan
+/// rule keeps us from deriving `rkyv::Archive` on it directly; this mirrors
+/// its three fields for the cache file and converts back on load.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
+#[archive(check_bytes)]
+struct CacheFv {
+	major: u32,
+	minor: u32,
+	patch: u32,
+}
+
+impl From<FactorioVersion> for CacheFv {
+	fn from(fv: FactorioVersion) -> Self {
+		CacheFv { major: fv.major, minor: fv.minor, patch: fv.patch }
+	}
+}
+
+impl From<CacheFv> for FactorioVersion {
+	fn from(fv: CacheFv) -> Self {
+		FactorioVersion::new(fv.major, fv.minor, fv.patch)
+	}
+}
+
+/// Everything `main` needs to skip both the SQLite query and the megabase
+/// index download: the raw per-map series (needed for the per-map charts),
+/// the checkpoint aggregation (needed for the collective charts), and the
+/// map name -> source link table fetched from the network.
+#[derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
+#[archive(check_bytes)]
+pub struct CachedData {
+	cache_key: u64,
+	pub maps: BTreeMap<MapInfo, BTreeMap<CacheFv, AvgData>>,
+	pub aggregation: BTreeMap<CacheFv, (Vec<MapInfo>, BTreeMap<CacheFv, AvgData>)>,
+	pub map_name_to_post_link: HashMap<String, String>,
+}
+
+pub struct Cached {
+	pub maps: BTreeMap<MapInfo, BTreeMap<FactorioVersion, AvgData>>,
+	pub aggregation: BTreeMap<FactorioVersion, (Vec<MapInfo>, BTreeMap<FactorioVersion, AvgData>)>,
+	pub map_name_to_post_link: HashMap<String, String>,
+}
+
+fn cache_path(db_loc: &Path) -> PathBuf {
+	let mut p = db_loc.as_os_str().to_owned();
+	p.push(".rkyv");
+	PathBuf::from(p)
+}
+
+/// Keys the cache off the source file's mtime and size, so edits to the
+/// underlying database (or a re-exported JSON archive) invalidate it.
+fn cache_key(db_loc: &Path) -> std::io::Result<u64> {
+	let metadata = std::fs::metadata(db_loc)?;
+	let mut hasher = DefaultHasher::new();
+	metadata.len().hash(&mut hasher);
+	metadata.modified()?.hash(&mut hasher);
+	Ok(hasher.finish())
+}
+
+fn fv_map<V>(map: BTreeMap<CacheFv, V>) -> BTreeMap<FactorioVersion, V> {
+	map.into_iter().map(|(fv, v)| (fv.into(), v)).collect()
+}
+
+/// Loads the cache for `db_loc` if present, fresh, and valid. Any mismatch
+/// (missing file, stale key, failed archive validation) is treated as a
+/// cache miss so the caller falls back to a full rebuild.
+///
+/// The `mmap` + `check_archived_root` validation itself is zero-copy: a
+/// stale or corrupt file is rejected without ever materializing owned data.
+/// Once validated, though, we `deserialize` the archive into owned
+/// `FactorioVersion`-keyed maps rather than handing back the `Archived*`
+/// view, since every downstream consumer (`aggregate_maps`, `gen_svg`, the
+/// `--serve`/`--check` paths) already works in terms of owned `FactorioVersion`
+/// (itself required by the `CacheFv` conversion below, per the orphan-rule
+/// note on `CacheFv`) rather than the archive's zero-copy types.
+pub fn try_load(db_loc: &Path) -> Option<Cached> {
+	let key = cache_key(db_loc).ok()?;
+	let file = std::fs::File::open(cache_path(db_loc)).ok()?;
+	let mmap = unsafe { Mmap::map(&file) }.ok()?;
+	let archived = match rkyv::check_archived_root::<CachedData>(&mmap) {
+		Ok(archived) => archived,
+		Err(e) => {
+			eprintln!("Cache for {:?} failed validation, rebuilding: {}", db_loc, e);
+			return None;
+		}
+	};
+	if archived.cache_key != key {
+		return None;
+	}
+	let data: CachedData = archived.deserialize(&mut rkyv::Infallible).ok()?;
+	Some(Cached {
+		maps: data.maps.into_iter().map(|(info, versions)| (info, fv_map(versions))).collect(),
+		aggregation: data
+			.aggregation
+			.into_iter()
+			.map(|(fv, (infos, versions))| (fv.into(), (infos, fv_map(versions))))
+			.collect(),
+		map_name_to_post_link: data.map_name_to_post_link,
+	})
+}
+
+/// Writes the queried+aggregated data and the megabase link table to
+/// `db_loc`'s `.rkyv` cache file for the next run to pick up.
+pub fn store(
+	db_loc: &Path,
+	maps: &BTreeMap<MapInfo, BTreeMap<FactorioVersion, AvgData>>,
+	aggregation: &BTreeMap<FactorioVersion, (Vec<MapInfo>, BTreeMap<FactorioVersion, AvgData>)>,
+	map_name_to_post_link: &HashMap<String, String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+	let cache_key = cache_key(db_loc)?;
+	let data = CachedData {
+		cache_key,
+		maps: maps.iter().map(|(info, versions)| (info.clone(), versions.iter().map(|(fv, d)| ((*fv).into(), *d)).collect())).collect(),
+		aggregation: aggregation
+			.iter()
+			.map(|(fv, (infos, versions))| ((*fv).into(), (infos.clone(), versions.iter().map(|(fv, d)| ((*fv).into(), *d)).collect())))
+			.collect(),
+		map_name_to_post_link: map_name_to_post_link.clone(),
+	};
+	let bytes = rkyv::to_bytes::<_, 4096>(&data)?;
+	std::fs::write(cache_path(db_loc), bytes)?;
+	Ok(())
+}
@@ -0,0 +1,135 @@
+use crate::{iter_factorio_versions, AvgData, MapInfo, END_GRAPH_FV, START_GRAPH_FV};
+use megabase_index_incrementer::FactorioVersion;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// Structured result of scanning a loaded data set for the kinds of upload
+/// mistakes that used to either panic `query_db` or silently corrupt a
+/// chart (a negative "other" slice in `gen_svg`).
+#[derive(Debug, Default)]
+pub struct CheckReport {
+	pub out_of_range: Vec<(MapInfo, FactorioVersion)>,
+	/// A `sha256` that was uploaded under more than one `map_name`.
+	pub sha256_with_multiple_names: Vec<(String, Vec<String>)>,
+	/// A `map_name` that was uploaded under more than one `sha256`.
+	pub map_name_with_multiple_sha256: Vec<(String, Vec<String>)>,
+	/// A version present in `iter_factorio_versions` that falls inside a
+	/// map's tested range but has no data for that map.
+	pub chain_gaps: Vec<(MapInfo, FactorioVersion)>,
+	/// `wholeUpdate` smaller than the sum of its components, which would
+	/// make the "other" slice `gen_svg` draws negative.
+	pub negative_other: Vec<(MapInfo, FactorioVersion)>,
+}
+
+impl CheckReport {
+	pub fn is_clean(&self) -> bool {
+		self.out_of_range.is_empty()
+			&& self.sha256_with_multiple_names.is_empty()
+			&& self.map_name_with_multiple_sha256.is_empty()
+			&& self.chain_gaps.is_empty()
+			&& self.negative_other.is_empty()
+	}
+
+	pub fn print_summary(&self) {
+		eprintln!("Checked data set: {} issue(s) found", self.issue_count());
+		if !self.out_of_range.is_empty() {
+			eprintln!("  {} version(s) outside {}..={}:", self.out_of_range.len(), START_GRAPH_FV, END_GRAPH_FV);
+			for (info, fv) in &self.out_of_range {
+				eprintln!("    {} ({}): {}", info.map_name, info.sha256, fv);
+			}
+		}
+		if !self.sha256_with_multiple_names.is_empty() {
+			eprintln!("  {} sha256(s) uploaded under more than one map_name:", self.sha256_with_multiple_names.len());
+			for (sha256, names) in &self.sha256_with_multiple_names {
+				eprintln!("    {}: {}", sha256, names.join(", "));
+			}
+		}
+		if !self.map_name_with_multiple_sha256.is_empty() {
+			eprintln!("  {} map_name(s) uploaded under more than one sha256:", self.map_name_with_multiple_sha256.len());
+			for (map_name, shas) in &self.map_name_with_multiple_sha256 {
+				eprintln!("    {}: {}", map_name, shas.join(", "));
+			}
+		}
+		if !self.chain_gaps.is_empty() {
+			eprintln!("  {} gap(s) in a map's tested version chain:", self.chain_gaps.len());
+			for (info, fv) in &self.chain_gaps {
+				eprintln!("    {} ({}) is missing {}", info.map_name, info.sha256, fv);
+			}
+		}
+		if !self.negative_other.is_empty() {
+			eprintln!("  {} instance(s) where wholeUpdate is smaller than the sum of its components:", self.negative_other.len());
+			for (info, fv) in &self.negative_other {
+				eprintln!("    {} ({}) at {}", info.map_name, info.sha256, fv);
+			}
+		}
+	}
+
+	fn issue_count(&self) -> usize {
+		self.out_of_range.len()
+			+ self.sha256_with_multiple_names.len()
+			+ self.map_name_with_multiple_sha256.len()
+			+ self.chain_gaps.len()
+			+ self.negative_other.len()
+	}
+}
+
+/// Scans a queried+aggregated data set and reports inconsistencies instead
+/// of panicking mid-query, so bad benchmark uploads are caught before chart
+/// generation.
+pub fn check(maps: &BTreeMap<MapInfo, BTreeMap<FactorioVersion, AvgData>>) -> CheckReport {
+	let mut report = CheckReport::default();
+
+	let mut names_by_sha256: HashMap<&str, HashSet<&str>> = HashMap::new();
+	let mut sha256s_by_name: HashMap<&str, HashSet<&str>> = HashMap::new();
+	for info in maps.keys() {
+		names_by_sha256.entry(&info.sha256).or_default().insert(&info.map_name);
+		sha256s_by_name.entry(&info.map_name).or_default().insert(&info.sha256);
+	}
+	for (sha256, names) in &names_by_sha256 {
+		if names.len() > 1 {
+			let mut names: Vec<String> = names.iter().map(|s| s.to_string()).collect();
+			names.sort();
+			report.sha256_with_multiple_names.push((sha256.to_string(), names));
+		}
+	}
+	report.sha256_with_multiple_names.sort();
+	for (map_name, shas) in &sha256s_by_name {
+		if shas.len() > 1 {
+			let mut shas: Vec<String> = shas.iter().map(|s| s.to_string()).collect();
+			shas.sort();
+			report.map_name_with_multiple_sha256.push((map_name.to_string(), shas));
+		}
+	}
+	report.map_name_with_multiple_sha256.sort();
+
+	let all_fvs = iter_factorio_versions();
+
+	for (info, versions_data) in maps {
+		for (fv, avg) in versions_data {
+			if *fv < START_GRAPH_FV || *fv > END_GRAPH_FV {
+				report.out_of_range.push((info.clone(), *fv));
+			}
+
+			let components = avg.circuitNetworkUpdate
+				+ avg.transportLinesUpdate
+				+ avg.fluidsUpdate
+				+ avg.entityUpdate
+				+ avg.electricNetworkUpdate
+				+ avg.logisticManagerUpdate
+				+ avg.trains
+				+ avg.trainPathFinder;
+			if avg.wholeUpdate < components {
+				report.negative_other.push((info.clone(), *fv));
+			}
+		}
+
+		if let (Some(min_fv), Some(max_fv)) = (versions_data.keys().min(), versions_data.keys().max()) {
+			for fv in &all_fvs {
+				if fv >= min_fv && fv <= max_fv && !versions_data.contains_key(fv) {
+					report.chain_gaps.push((info.clone(), *fv));
+				}
+			}
+		}
+	}
+
+	report
+}
@@ -0,0 +1,193 @@
+use crate::{AvgData, MapInfo};
+use megabase_index_incrementer::FactorioVersion;
+use rusqlite::types::Type;
+use rusqlite::Connection;
+use rusqlite::NO_PARAMS;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::path::{Path, PathBuf};
+
+/// Wraps a `FactorioVersion` parse failure so it can travel through
+/// `rusqlite`'s row-mapping `Result` instead of being `unwrap()`-ed, letting
+/// a single unparseable row surface as an error from `load` rather than
+/// panicking the whole query. `--check` does not report such rows; the
+/// error ends the run before the check starts.
+#[derive(Debug)]
+struct VersionParseError(String);
+
+impl std::fmt::Display for VersionParseError {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		write!(f, "could not parse factorio_version: {}", self.0)
+	}
+}
+
+impl std::error::Error for VersionParseError {}
+
+/// Anything that can produce the aggregated "map -> version -> averages" table
+/// that the rest of the pipeline (`aggregate_maps`, `gen_svg`) operates on.
+///
+/// `query_db` used to be the only way to get this data; it is now just the
+/// `SqliteSource` implementation, and `JsonSource` lets the same pipeline run
+/// against an exported archive instead of the original regression.db.
+pub trait DataSource {
+	fn load(&self) -> Result<BTreeMap<MapInfo, BTreeMap<FactorioVersion, AvgData>>, Box<dyn std::error::Error>>;
+}
+
+/// Reads straight from a factorio-benchmark-helper `regression.db`.
+pub struct SqliteSource {
+	pub db_loc: PathBuf,
+}
+
+impl SqliteSource {
+	pub fn new<P: AsRef<Path>>(db_loc: P) -> Self {
+		SqliteSource { db_loc: db_loc.as_ref().to_owned() }
+	}
+}
+
+impl DataSource for SqliteSource {
+	fn load(&self) -> Result<BTreeMap<MapInfo, BTreeMap<FactorioVersion, AvgData>>, Box<dyn std::error::Error>> {
+		query_db(&self.db_loc)
+	}
+}
+
+pub fn query_db<P: AsRef<Path>>(db_loc: P) -> Result<BTreeMap<MapInfo, BTreeMap<FactorioVersion, AvgData>>, Box<dyn std::error::Error>> {
+	if !db_loc.as_ref().exists() {
+		return Err(format!("Could not find a suitable regression test database. Try running factorio-benchmark-helper with the --regression-test flag, was passed {:?}", db_loc.as_ref()).into());
+	}
+	let db = Connection::open(db_loc)?;
+    // Define chart related sizes.
+	let mut stmt = db.prepare(
+r#"select factorio_version,
+avg(wholeUpdate)/1000000.0 as wholeUpdate,
+avg(circuitNetworkUpdate)/1000000.0 as circuitNetworkUpdate,
+avg(transportLinesUpdate)/1000000.0 as transportLinesUpdate,
+avg(fluidsUpdate)/1000000.0 as fluidsUpdate,
+avg(entityUpdate)/1000000.0 as entityUpdate,
+avg(electricNetworkUpdate)/1000000.0 as electricNetworkUpdate,
+avg(logisticManagerUpdate)/1000000.0 as logisticMangerUpdate,
+avg(trains)/1000000.0 as trains,
+avg(trainPathFinder)/1000000.0 as trainPathFinder,
+sha256,
+map_name
+from verbose join regression_test_instance
+on verbose.instance_ID = regression_test_instance.ID
+join regression_scenario
+on regression_scenario.ID = regression_test_instance.scenario_ID
+group by instance_id
+order by scenario_ID, factorio_version;"#)?;
+
+	let data = stmt.query_map(NO_PARAMS, |row| {
+		// Versions outside START_GRAPH_FV..=END_GRAPH_FV used to be an assert
+		// here, aborting the whole run on one bad row; that's now a `--check`
+		// report entry (see `check::check`) instead, so a single stray upload
+		// doesn't take down every other chart. An unparseable version string
+		// is propagated as a row error for the same reason, rather than
+		// unwrapped, so it surfaces as a normal error instead of a panic.
+		let fv = FactorioVersion::try_from(row.get::<_, String>(0)?.as_ref())
+			.map_err(|e| rusqlite::Error::FromSqlConversionFailure(0, Type::Text, Box::new(VersionParseError(format!("{:?}", e)))))?;
+		Ok((
+			fv,
+			AvgData {
+				wholeUpdate: row.get(1)?,
+				circuitNetworkUpdate: row.get(2)?,
+				transportLinesUpdate: row.get(3)?,
+				fluidsUpdate: row.get(4)?,
+				entityUpdate: row.get(5)?,
+				electricNetworkUpdate: row.get(6)?,
+				logisticManagerUpdate: row.get(7)?,
+				trains: row.get(8)?,
+				trainPathFinder: row.get(9)?,
+
+			},
+			MapInfo {
+				sha256: row.get(10)?,
+				map_name: row.get(11)?,
+			}
+	))
+	})?;
+	let mut maps = BTreeMap::new();
+
+	for mapped_row in data {
+		let (fv, data, map_info) = mapped_row?;
+		let entry = maps.entry(map_info).or_insert_with(BTreeMap::new);
+		entry.insert(fv, data);
+	}
+
+	Ok(maps)
+}
+
+/// Flat on-disk shape used by [`JsonSource`] and [`export_json`]. Versions are
+/// stored as their string form (`FactorioVersion`'s `Display`/`TryFrom<&str>`
+/// round-trip) so the file stays human-readable and diffable.
+#[derive(Debug, Serialize, Deserialize)]
+struct JsonMapRecord {
+	map_name: String,
+	sha256: String,
+	versions: BTreeMap<String, AvgData>,
+}
+
+/// Reads a flat file produced by [`export_json`], letting users archive and
+/// share a queried+aggregated benchmark set without the original SQLite file.
+pub struct JsonSource {
+	pub path: PathBuf,
+}
+
+impl JsonSource {
+	pub fn new<P: AsRef<Path>>(path: P) -> Self {
+		JsonSource { path: path.as_ref().to_owned() }
+	}
+}
+
+impl DataSource for JsonSource {
+	fn load(&self) -> Result<BTreeMap<MapInfo, BTreeMap<FactorioVersion, AvgData>>, Box<dyn std::error::Error>> {
+		let s = std::fs::read_to_string(&self.path)?;
+		let records: Vec<JsonMapRecord> = serde_json::from_str(&s)?;
+		let mut maps = BTreeMap::new();
+		for record in records {
+			let mut versions = BTreeMap::new();
+			for (fv, data) in record.versions {
+				versions.insert(FactorioVersion::try_from(fv.as_ref())?, data);
+			}
+			maps.insert(MapInfo { map_name: record.map_name, sha256: record.sha256 }, versions);
+		}
+		Ok(maps)
+	}
+}
+
+/// Dumps a queried+aggregated data set to the flat JSON format read by
+/// [`JsonSource`], for the `convert` subcommand.
+pub fn export_json<P: AsRef<Path>>(
+	maps: &BTreeMap<MapInfo, BTreeMap<FactorioVersion, AvgData>>,
+	out: P,
+) -> Result<(), Box<dyn std::error::Error>> {
+	let records: Vec<JsonMapRecord> = maps
+		.iter()
+		.map(|(info, versions_data)| JsonMapRecord {
+			map_name: info.map_name.clone(),
+			sha256: info.sha256.clone(),
+			versions: versions_data.iter().map(|(fv, data)| (fv.to_string(), *data)).collect(),
+		})
+		.collect();
+	let s = serde_json::to_string_pretty(&records)?;
+	std::fs::write(out, s)?;
+	Ok(())
+}
+
+/// Picks a [`DataSource`] for `path`, preferring an explicit `--format` value
+/// (`"sqlite"`/`"json"`) and falling back to the file extension.
+pub fn source_for(path: &Path, format: Option<&str>) -> Result<Box<dyn DataSource>, Box<dyn std::error::Error>> {
+	let format = match format {
+		Some(f) => f.to_owned(),
+		None => path
+			.extension()
+			.and_then(|e| e.to_str())
+			.map(|e| e.to_lowercase())
+			.ok_or("Could not determine a data source format from the file extension; pass --format")?,
+	};
+	match format.as_str() {
+		"json" => Ok(Box::new(JsonSource::new(path))),
+		"db" | "sqlite" | "sqlite3" => Ok(Box::new(SqliteSource::new(path))),
+		other => Err(format!("Unknown data source format {:?}, expected \"json\" or \"sqlite\"", other).into()),
+	}
+}
@@ -1,15 +1,17 @@
 use core::ops::AddAssign;
 use megabase_index_incrementer::*;
-use std::path::Path;
 use std::path::PathBuf;
-use std::convert::TryFrom;
 use charts::{Chart, ScaleLinear,
 	ScaleBand, VerticalBarView, BarLabelPosition, AxisPosition};
-use rusqlite::Connection;
-use rusqlite::NO_PARAMS;
 use std::collections::HashMap;
 use std::collections::BTreeMap;
 
+mod cache;
+mod check;
+mod data_source;
+mod server;
+use data_source::{source_for, DataSource};
+
 //const LAST_MAJOR_VERSION: FactorioVersion = FactorioVersion::new(0,18,45???);
 
 const LAST_MINOR_VERSIONS: [FactorioVersion; 2] = [
@@ -42,17 +44,18 @@ fn iter_factorio_versions() -> Vec<FactorioVersion> {
 
 /// A collection of averaged data for a given factorio version
 #[allow(non_snake_case)]
-#[derive(Debug, Clone, PartialEq, Copy, Default)]
+#[derive(Debug, Clone, PartialEq, Copy, Default, serde::Serialize, serde::Deserialize, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
+#[archive(check_bytes)]
 struct AvgData {
-	wholeUpdate: f64,
-	circuitNetworkUpdate: f64,
-	transportLinesUpdate: f64,
-	fluidsUpdate: f64,
-	entityUpdate: f64,
-	electricNetworkUpdate: f64,
-	logisticManagerUpdate: f64,
-	trains: f64,
-	trainPathFinder: f64,
+	pub(crate) wholeUpdate: f64,
+	pub(crate) circuitNetworkUpdate: f64,
+	pub(crate) transportLinesUpdate: f64,
+	pub(crate) fluidsUpdate: f64,
+	pub(crate) entityUpdate: f64,
+	pub(crate) electricNetworkUpdate: f64,
+	pub(crate) logisticManagerUpdate: f64,
+	pub(crate) trains: f64,
+	pub(crate) trainPathFinder: f64,
 }
 
 impl AddAssign for AvgData {
@@ -69,71 +72,11 @@ impl AddAssign for AvgData {
 	}
 }
 
-#[derive(Debug, Hash, PartialEq, Eq, Clone, PartialOrd, Ord)]
+#[derive(Debug, Hash, PartialEq, Eq, Clone, PartialOrd, Ord, serde::Serialize, serde::Deserialize, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
+#[archive(check_bytes)]
 struct MapInfo {
-	map_name: String,
-	sha256: String,
-}
-
-fn query_db<P: AsRef<Path>>(db_loc: P) -> Result<BTreeMap<MapInfo, BTreeMap<FactorioVersion, AvgData>>, Box<dyn std::error::Error>> {
-	if !db_loc.as_ref().exists() {
-		panic!("Could not find a suitable regression test database. Try running factorio-benchmark-helper with the --regression-test flag, was passed {:?}", db_loc.as_ref());
-	}
-	let db = Connection::open(db_loc)?;
-    // Define chart related sizes.
-	let mut stmt = db.prepare(
-r#"select factorio_version,
-avg(wholeUpdate)/1000000.0 as wholeUpdate,
-avg(circuitNetworkUpdate)/1000000.0 as circuitNetworkUpdate,
-avg(transportLinesUpdate)/1000000.0 as transportLinesUpdate,
-avg(fluidsUpdate)/1000000.0 as fluidsUpdate,
-avg(entityUpdate)/1000000.0 as entityUpdate,
-avg(electricNetworkUpdate)/1000000.0 as electricNetworkUpdate,
-avg(logisticManagerUpdate)/1000000.0 as logisticMangerUpdate,
-avg(trains)/1000000.0 as trains,
-avg(trainPathFinder)/1000000.0 as trainPathFinder,
-sha256,
-map_name
-from verbose join regression_test_instance
-on verbose.instance_ID = regression_test_instance.ID
-join regression_scenario
-on regression_scenario.ID = regression_test_instance.scenario_ID
-group by instance_id
-order by scenario_ID, factorio_version;"#)?;
-
-	let data = stmt.query_map(NO_PARAMS, |row| {
-		let fv = FactorioVersion::try_from(row.get::<_, String>(0)?.as_ref()).unwrap();
-		assert!(fv <= END_GRAPH_FV, "Factorio version {} exceeds END_GRAPH_FV", fv.to_string());
-		assert!(fv >= START_GRAPH_FV, "Factorio version {} precedes START_GRAPH_FV", fv.to_string());
-		Ok((
-			fv,
-			AvgData {
-				wholeUpdate: row.get(1)?,
-				circuitNetworkUpdate: row.get(2)?,
-				transportLinesUpdate: row.get(3)?,
-				fluidsUpdate: row.get(4)?,
-				entityUpdate: row.get(5)?,
-				electricNetworkUpdate: row.get(6)?,
-				logisticManagerUpdate: row.get(7)?,
-				trains: row.get(8)?,
-				trainPathFinder: row.get(9)?,
-
-			},
-			MapInfo {
-				sha256: row.get(10)?,
-				map_name: row.get(11)?,
-			}
-	))
-	})?;
-	let mut maps = BTreeMap::new();
-
-	for mapped_row in data {
-		let (fv, data, map_info) = mapped_row?;
-		let entry = maps.entry(map_info).or_insert_with(BTreeMap::new);
-		entry.insert(fv, data);
-	}
-
-	Ok(maps)
+	pub(crate) map_name: String,
+	pub(crate) sha256: String,
 }
 
 /// Aggregate-transforms a set of Maps that have been tested in various Factorio
@@ -286,9 +229,9 @@ fn gen_svg(collective_fv: Option<FactorioVersion>, map_infos: &[MapInfo], versio
 }
 
 struct HtmlEmitter {
-	svg: PathBuf,
-	sel_list_name: String,
-	ext_descr: Vec<(String, String)>,
+	pub(crate) svg: PathBuf,
+	pub(crate) sel_list_name: String,
+	pub(crate) ext_descr: Vec<(String, String)>,
 }
 
 /// Downloads and parses the technicalfactorio megabase index.
@@ -305,35 +248,115 @@ fn fetch_megabase_list() -> Result<Megabases, Box<dyn std::error::Error>> {
     }
 }
 
+/// Pulls `--format <json|sqlite>` out of the argument list if present,
+/// returning the remaining positional arguments alongside it.
+fn take_format_flag(mut args: Vec<String>) -> (Vec<String>, Option<String>) {
+	if let Some(idx) = args.iter().position(|a| a == "--format") {
+		args.remove(idx);
+		if idx < args.len() {
+			let format = args.remove(idx);
+			return (args, Some(format));
+		}
+	}
+	(args, None)
+}
+
+/// Pulls a bare flag like `--no-cache` out of the argument list if present.
+fn take_bool_flag(mut args: Vec<String>, flag: &str) -> (Vec<String>, bool) {
+	if let Some(idx) = args.iter().position(|a| a == flag) {
+		args.remove(idx);
+		(args, true)
+	} else {
+		(args, false)
+	}
+}
+
+const DEFAULT_SERVE_ADDR: &str = "127.0.0.1:8000";
+
+/// Pulls `--serve [addr:port]` out of the argument list if present. The
+/// address is optional; a bare `--serve` binds to [`DEFAULT_SERVE_ADDR`].
+fn take_serve_flag(mut args: Vec<String>) -> (Vec<String>, Option<String>) {
+	if let Some(idx) = args.iter().position(|a| a == "--serve") {
+		args.remove(idx);
+		if idx < args.len() && args[idx].contains(':') {
+			let addr = args.remove(idx);
+			return (args, Some(addr));
+		}
+		return (args, Some(DEFAULT_SERVE_ADDR.to_owned()));
+	}
+	(args, None)
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
 	let args = std::env::args();
 	let args: Vec<_> = args.collect();
 	if args.is_empty() || args.len() == 1 {
-		eprintln!("Usage: cargo run -- $PATH_TO_REGRESSION_DB");
+		eprintln!("Usage: cargo run -- $PATH_TO_REGRESSION_DB [--format json|sqlite] [--no-cache] [--serve [addr:port]]");
 		eprintln!("cargo run -- --default to attempt to use the below path");
 		eprintln!("Probably ~/.local/share/factorio-benchmark-helper/regression-testing/regression.db");
+		eprintln!("cargo run -- convert $SOURCE $OUTPUT.json to re-export a source's queried+aggregated data as JSON");
+		eprintln!("cargo run -- $PATH_TO_REGRESSION_DB --check to scan for data inconsistencies instead of generating charts");
 		std::process::exit(0);
 	}
-	let path = if args[args.len() - 1] == "--default" {
+
+	if args[1] == "convert" {
+		if args.len() < 4 {
+			eprintln!("Usage: cargo run -- convert $SOURCE $OUTPUT.json [--format json|sqlite]");
+			std::process::exit(1);
+		}
+		let (positional, format) = take_format_flag(args[2..].to_vec());
+		let source_path = PathBuf::from(&positional[0]);
+		let out_path = PathBuf::from(&positional[1]);
+		let maps = source_for(&source_path, format.as_deref())?.load()?;
+		data_source::export_json(&maps, out_path)?;
+		return Ok(());
+	}
+
+	let (positional, format) = take_format_flag(args[1..].to_vec());
+	let (positional, no_cache) = take_bool_flag(positional, "--no-cache");
+	let (positional, serve_addr) = take_serve_flag(positional);
+	let (positional, run_check) = take_bool_flag(positional, "--check");
+	if positional.is_empty() {
+		eprintln!("Usage: cargo run -- $PATH_TO_REGRESSION_DB [--format json|sqlite] [--no-cache] [--serve [addr:port]]");
+		eprintln!("cargo run -- --default to attempt to use the below path");
+		eprintln!("Probably ~/.local/share/factorio-benchmark-helper/regression-testing/regression.db");
+		std::process::exit(0);
+	}
+	let path = if positional[positional.len() - 1] == "--default" {
 		let p = PathBuf::from(".local/share/factorio-benchmark-helper/regression-testing/regression.db");
 		#[allow(deprecated)]
 		std::env::home_dir().unwrap().join(p)
 	} else {
-		PathBuf::from(&args[args.len() - 1])
+		PathBuf::from(&positional[positional.len() - 1])
 	};
 
-	let megabases = fetch_megabase_list()?;
-	let mut map_name_to_post_link = HashMap::new();
-	for megabase in megabases.saves {
-		map_name_to_post_link.insert(megabase.name, megabase.source_link);
+	if run_check {
+		let maps = source_for(&path, format.as_deref())?.load()?;
+		let report = check::check(&maps);
+		report.print_summary();
+		std::process::exit(if report.is_clean() { 0 } else { 1 });
 	}
 
-	// You should point this at the actual regression testing database
-	// it probably lives in
+	// You should point this at the actual regression testing database (or a
+	// JSON archive produced by `convert`); it probably lives in
 	// ~/.local/share/factorio-benchmark-helper/regression-testing/regression.db
-	let maps = query_db(path)?;
+	let cached = if no_cache { None } else { cache::try_load(&path) };
+	let (maps, aggregation, map_name_to_post_link) = if let Some(cached) = cached {
+		(cached.maps, cached.aggregation, cached.map_name_to_post_link)
+	} else {
+		let megabases = fetch_megabase_list()?;
+		let mut map_name_to_post_link = HashMap::new();
+		for megabase in megabases.saves {
+			map_name_to_post_link.insert(megabase.name, megabase.source_link);
+		}
 
-	let aggregation = aggregate_maps(&maps);
+		let maps = source_for(&path, format.as_deref())?.load()?;
+		let aggregation = aggregate_maps(&maps);
+		if let Err(e) = cache::store(&path, &maps, &aggregation, &map_name_to_post_link) {
+			eprintln!("Failed to write cache for {:?}: {}", path, e);
+		}
+		(maps, aggregation, map_name_to_post_link)
+	};
 	let mut html_emitters = Vec::new();
 
 	for (fv, (map_info, avg_data)) in aggregation {
@@ -357,6 +380,10 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 		html_emitters.insert(0, template);
 	}
 
+	if let Some(addr) = serve_addr {
+		return server::serve(&addr, html_emitters);
+	}
+
 	eprintln!("<select class=\"selections\">");
 	for emitter in &html_emitters {
 		eprintln!("    <option onclick = \"setSlide()\">{}</option>", emitter.sel_list_name);
@@ -395,7 +422,7 @@ mod tests {
 		let p = PathBuf::from(".local/share/factorio-benchmark-helper/regression-testing/regression.db");
 		#[allow(deprecated)]
 		let path = std::env::home_dir().unwrap().join(p);
-		let maps = query_db(path).unwrap();
+		let maps = data_source::query_db(path).unwrap();
 
 		aggregate_maps(&maps);
 	}
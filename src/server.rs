@@ -0,0 +1,135 @@
+use crate::HtmlEmitter;
+use std::collections::HashMap;
+
+/// Escapes text for safe interpolation into HTML element content or a
+/// double-quoted attribute value.
+fn escape_html(s: &str) -> String {
+	s.replace('&', "&amp;")
+		.replace('<', "&lt;")
+		.replace('>', "&gt;")
+		.replace('"', "&quot;")
+		.replace('\'', "&#39;")
+}
+
+/// Percent-encodes a single path segment (e.g. an SVG filename) so it's a
+/// valid, space-free URL; pairs with [`percent_decode`] on the server side.
+fn percent_encode_segment(s: &str) -> String {
+	let mut out = String::new();
+	for b in s.bytes() {
+		match b {
+			b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => out.push(b as char),
+			_ => out.push_str(&format!("%{:02X}", b)),
+		}
+	}
+	out
+}
+
+/// Value of a single ASCII hex digit, or `None` if `b` is not one.
+fn hex_digit(b: u8) -> Option<u8> {
+	match b {
+		b'0'..=b'9' => Some(b - b'0'),
+		b'a'..=b'f' => Some(b - b'a' + 10),
+		b'A'..=b'F' => Some(b - b'A' + 10),
+		_ => None,
+	}
+}
+
+/// Decodes `%XX` escapes in a URL path segment. `tiny_http::Request::url()`
+/// returns the raw, un-decoded request target, so this must run before
+/// looking the segment up in a table keyed by plain filenames.
+fn percent_decode(s: &str) -> String {
+	let bytes = s.as_bytes();
+	let mut out = Vec::with_capacity(bytes.len());
+	let mut i = 0;
+	while i < bytes.len() {
+		if bytes[i] == b'%' && i + 2 < bytes.len() {
+			// Work byte-by-byte rather than string-slicing `s`: the two
+			// bytes after a `%` may sit in the middle of a multi-byte UTF-8
+			// character (e.g. a stray `%` right before one), and slicing a
+			// `&str` on a non-char-boundary panics.
+			if let (Some(hi), Some(lo)) = (hex_digit(bytes[i + 1]), hex_digit(bytes[i + 2])) {
+				out.push(hi << 4 | lo);
+				i += 3;
+				continue;
+			}
+		}
+		out.push(bytes[i]);
+		i += 1;
+	}
+	String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Renders the emitter list into a complete HTML document: the same
+/// `<select>`/`setSlide()` slideshow `main` otherwise dumps as a bare
+/// fragment to stderr, but wrapped with the `<head>`/CSS/JS needed to view
+/// it directly in a browser.
+fn render_document(emitters: &[HtmlEmitter]) -> String {
+	let mut out = String::new();
+	out.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>visgression</title>\n<style>\n");
+	out.push_str(".slide { display: none; }\n.slide.active { display: block; }\nimg { max-width: 100%; }\n");
+	out.push_str("</style>\n<script>\nfunction setSlide(i) {\n");
+	out.push_str("    var slides = document.getElementsByClassName(\"slide\");\n");
+	out.push_str("    for (var j = 0; j < slides.length; j++) { slides[j].classList.toggle(\"active\", j === i); }\n");
+	out.push_str("}\nwindow.onload = function() { setSlide(0); };\n</script>\n</head>\n<body>\n");
+
+	out.push_str("<select class=\"selections\">\n");
+	for (i, emitter) in emitters.iter().enumerate() {
+		out.push_str(&format!("    <option onclick=\"setSlide({})\">{}</option>\n", i, escape_html(&emitter.sel_list_name)));
+	}
+	out.push_str("</select>\n");
+
+	out.push_str("<div class=\"slides\">\n");
+	for (i, emitter) in emitters.iter().enumerate() {
+		let active = if i == 0 { " active" } else { "" };
+		out.push_str(&format!("    <div class=\"slide{}\">\n", active));
+		let svg_name = percent_encode_segment(emitter.svg.to_str().unwrap());
+		out.push_str(&format!("        <img src=\"/images/{}\"/>\n", svg_name));
+		if !emitter.ext_descr.is_empty() {
+			out.push_str("        <ul>\n");
+			for (post_link, desc) in &emitter.ext_descr {
+				out.push_str(&format!("            <li><a href=\"{}\">{}</a></li>\n", escape_html(post_link), escape_html(desc)));
+			}
+			out.push_str("        </ul>\n");
+		}
+		out.push_str("    </div>\n");
+	}
+	out.push_str("</div>\n</body>\n</html>\n");
+	out
+}
+
+/// Starts a blocking HTTP server at `addr` that serves the rendered
+/// slideshow at `/` and each generated SVG (read into memory once up front)
+/// at `/images/<name>.svg`, so charts can be browsed without copying files
+/// into an external web root.
+pub fn serve(addr: &str, emitters: Vec<HtmlEmitter>) -> Result<(), Box<dyn std::error::Error>> {
+	let mut images = HashMap::new();
+	for emitter in &emitters {
+		let name = emitter.svg.to_str().unwrap().to_owned();
+		let bytes = std::fs::read(&emitter.svg)?;
+		images.insert(name, bytes);
+	}
+	let document = render_document(&emitters);
+
+	let server = tiny_http::Server::http(addr).map_err(|e| format!("Failed to bind {}: {}", addr, e))?;
+	eprintln!("Serving slideshow on http://{}/", addr);
+	for request in server.incoming_requests() {
+		let url = request.url().to_owned();
+		if url == "/" {
+			let header = tiny_http::Header::from_bytes(&b"Content-Type"[..], &b"text/html; charset=utf-8"[..]).unwrap();
+			let response = tiny_http::Response::from_string(document.clone()).with_header(header);
+			let _ = request.respond(response);
+		} else if let Some(name) = url.strip_prefix("/images/") {
+			let name = percent_decode(name);
+			if let Some(bytes) = images.get(&name) {
+				let header = tiny_http::Header::from_bytes(&b"Content-Type"[..], &b"image/svg+xml"[..]).unwrap();
+				let response = tiny_http::Response::from_data(bytes.clone()).with_header(header);
+				let _ = request.respond(response);
+			} else {
+				let _ = request.respond(tiny_http::Response::from_string("not found").with_status_code(404));
+			}
+		} else {
+			let _ = request.respond(tiny_http::Response::from_string("not found").with_status_code(404));
+		}
+	}
+	Ok(())
+}